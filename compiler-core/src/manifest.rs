@@ -2,13 +2,38 @@ use std::collections::HashMap;
 use std::path::PathBuf;
 
 use crate::requirement::Requirement;
+use crate::Error;
 use crate::Result;
+use crate::{FileIoAction, FileKind};
 use hexpm::version::Version;
 use itertools::Itertools;
 use smol_str::SmolStr;
 
+/// The current version of the `manifest.toml` format. This is bumped
+/// whenever the on-disk shape of the manifest changes in a way that isn't
+/// simply additive, so that manifests written by older versions of Gleam
+/// can be migrated forward instead of silently misparsed.
+pub const MANIFEST_VERSION: u32 = 1;
+
+fn default_manifest_version() -> u32 {
+    1
+}
+
+/// A single step in the migration table below. Each entry upgrades a
+/// manifest encoded in one version into the encoding used by the next
+/// version up, operating on the raw TOML value before it is parsed into a
+/// `Manifest`.
+type Migration = fn(toml::Value) -> Result<toml::Value>;
+
+// There have been no format changes yet, so this table is empty. When the
+// on-disk shape changes again a migration gets appended here rather than
+// breaking older lockfiles.
+const MIGRATIONS: &[Migration] = &[];
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 pub struct Manifest {
+    #[serde(default = "default_manifest_version")]
+    pub version: u32,
     #[serde(serialize_with = "ordered_map")]
     pub requirements: HashMap<String, Requirement>,
     #[serde(serialize_with = "sorted_vec")]
@@ -16,6 +41,47 @@ pub struct Manifest {
 }
 
 impl Manifest {
+    /// Parse a `manifest.toml` file, migrating it to the current format
+    /// version if it was written by an older version of Gleam, and
+    /// refusing to load it if it declares a version newer than this
+    /// compiler understands.
+    pub fn from_toml(src: &str) -> Result<Self> {
+        let mut value: toml::Value = toml::from_str(src)
+            .map_err(|e| Error::InvalidManifestFormat { error: e.to_string() })?;
+
+        let version = value
+            .get("version")
+            .and_then(toml::Value::as_integer)
+            .map(|version| version as u32)
+            .unwrap_or_else(default_manifest_version);
+
+        if version > MANIFEST_VERSION {
+            return Err(Error::UnknownManifestVersion {
+                version,
+                newest_supported: MANIFEST_VERSION,
+            });
+        }
+
+        for migration in MIGRATIONS.iter().skip(version.saturating_sub(1) as usize) {
+            value = migration(value)?;
+        }
+
+        value
+            .try_into()
+            .map_err(|e: toml::de::Error| Error::InvalidManifestFormat { error: e.to_string() })
+    }
+
+    /// Extract every vendored `Archive` package into `build_packages_dir`,
+    /// without fetching anything from Hex or a git remote. Packages with
+    /// other sources are skipped. Dependency resolution does not call this;
+    /// archives are extracted only when it is invoked directly.
+    pub fn ensure_archives_extracted(&self, build_packages_dir: &std::path::Path) -> Result<()> {
+        for package in &self.packages {
+            package.ensure_archive_extracted(build_packages_dir)?;
+        }
+        Ok(())
+    }
+
     // Rather than using the toml library to do serialization we implement it
     // manually so that we can control the formatting.
     // We want to keep entries on a single line each so that they are more
@@ -23,6 +89,7 @@ impl Manifest {
     pub fn to_toml(&self) -> String {
         let mut buffer = String::new();
         let Self {
+            version,
             requirements,
             packages,
         } = self;
@@ -34,6 +101,8 @@ impl Manifest {
 ",
         );
 
+        buffer.push_str(&format!("version = {version}\n\n"));
+
         // Packages
         buffer.push_str("packages = [\n");
         for ManifestPackage {
@@ -43,6 +112,7 @@ impl Manifest {
             otp_app,
             build_tools,
             requirements,
+            signature,
         } in packages.iter().sorted_by(|a, b| a.name.cmp(&b.name))
         {
             buffer.push_str(r#"  {"#);
@@ -78,15 +148,33 @@ impl Manifest {
             }
 
             match source {
-                ManifestPackageSource::Hex { outer_checksum } => {
+                ManifestPackageSource::Hex {
+                    outer_checksum,
+                    inner_checksum,
+                } => {
                     buffer.push_str(r#", source = "hex", outer_checksum = ""#);
                     buffer.push_str(&outer_checksum.to_string());
                     buffer.push('"');
+                    if let Some(inner_checksum) = inner_checksum {
+                        buffer.push_str(r#", inner_checksum = ""#);
+                        buffer.push_str(&inner_checksum.to_string());
+                        buffer.push('"');
+                    }
                 }
-                ManifestPackageSource::Git { repo, commit } => {
+                ManifestPackageSource::Git {
+                    repo,
+                    commit,
+                    ref_,
+                } => {
                     buffer.push_str(r#", source = "git", repo = ""#);
                     buffer.push_str(repo);
-                    buffer.push_str(r#"", commit = ""#);
+                    buffer.push('"');
+                    if let Some(ref_) = ref_ {
+                        buffer.push_str(r#", ref = ""#);
+                        buffer.push_str(ref_);
+                        buffer.push('"');
+                    }
+                    buffer.push_str(r#", commit = ""#);
                     buffer.push_str(commit);
                     buffer.push('"');
                 }
@@ -95,8 +183,27 @@ impl Manifest {
                     buffer.push_str(path.to_str().expect("local path non utf-8"));
                     buffer.push('"');
                 }
+                ManifestPackageSource::Archive {
+                    path,
+                    outer_checksum,
+                    compression,
+                } => {
+                    buffer.push_str(r#", source = "archive", path = ""#);
+                    buffer.push_str(path.to_str().expect("archive path non utf-8"));
+                    buffer.push_str(r#"", compression = ""#);
+                    buffer.push_str(compression.as_str());
+                    buffer.push_str(r#"", outer_checksum = ""#);
+                    buffer.push_str(&outer_checksum.to_string());
+                    buffer.push('"');
+                }
             };
 
+            if let Some(signature) = signature {
+                buffer.push_str(r#", signature = ""#);
+                buffer.push_str(signature);
+                buffer.push('"');
+            }
+
             buffer.push_str(" },\n");
         }
         buffer.push_str("]\n\n");
@@ -117,6 +224,7 @@ impl Manifest {
 #[test]
 fn manifest_toml_format() {
     let mut manifest = Manifest {
+        version: 1,
         requirements: [
             ("zzz".into(), Requirement::hex("> 0.0.0")),
             ("aaa".into(), Requirement::hex("> 0.0.0")),
@@ -139,8 +247,10 @@ fn manifest_toml_format() {
                 build_tools: ["gleam".into()].into(),
                 otp_app: None,
                 requirements: vec![],
+                signature: Some("rWE6v1ClCiMoeQ4fQtP5n2n2i4QjQeKjw6WuMK5Wjk4=".into()),
                 source: ManifestPackageSource::Hex {
                     outer_checksum: Base16Checksum(vec![1, 22]),
+                    inner_checksum: Some(Base16Checksum(vec![4, 44])),
                 },
             },
             ManifestPackage {
@@ -149,8 +259,10 @@ fn manifest_toml_format() {
                 build_tools: ["rebar3".into(), "make".into()].into(),
                 otp_app: Some("aaa_app".into()),
                 requirements: vec!["zzz".into(), "gleam_stdlib".into()],
+                signature: None,
                 source: ManifestPackageSource::Hex {
                     outer_checksum: Base16Checksum(vec![3, 22]),
+                    inner_checksum: None,
                 },
             },
             ManifestPackage {
@@ -159,8 +271,10 @@ fn manifest_toml_format() {
                 build_tools: ["mix".into()].into(),
                 otp_app: None,
                 requirements: vec![],
+                signature: None,
                 source: ManifestPackageSource::Hex {
                     outer_checksum: Base16Checksum(vec![3, 22]),
+                    inner_checksum: None,
                 },
             },
             ManifestPackage {
@@ -169,8 +283,10 @@ fn manifest_toml_format() {
                 build_tools: ["gleam".into()].into(),
                 otp_app: None,
                 requirements: vec![],
+                signature: None,
                 source: ManifestPackageSource::Git {
                     repo: "https://github.com/gleam-lang/gleam.git".into(),
+                    ref_: Some("main".into()),
                     commit: "bd9fe02f72250e6a136967917bcb1bdccaffa3c8".into(),
                 },
             },
@@ -180,18 +296,34 @@ fn manifest_toml_format() {
                 build_tools: ["gleam".into()].into(),
                 otp_app: None,
                 requirements: vec![],
+                signature: None,
                 source: ManifestPackageSource::Local {
                     path: "/home/louis/packages/path/to/package".into(),
                 },
             },
+            ManifestPackage {
+                name: "vendored_pkg".into(),
+                version: Version::new(2, 0, 0),
+                build_tools: ["gleam".into()].into(),
+                otp_app: None,
+                requirements: vec![],
+                signature: None,
+                source: ManifestPackageSource::Archive {
+                    path: "vendor/vendored_pkg-2.0.0.tar.zst".into(),
+                    outer_checksum: Base16Checksum(vec![5, 66]),
+                    compression: Compression::Zstd,
+                },
+            },
             ManifestPackage {
                 name: "gleeunit".into(),
                 version: Version::new(0, 4, 0),
                 build_tools: ["gleam".into()].into(),
                 otp_app: None,
                 requirements: vec!["gleam_stdlib".into()],
+                signature: None,
                 source: ManifestPackageSource::Hex {
                     outer_checksum: Base16Checksum(vec![3, 46]),
+                    inner_checksum: None,
                 },
             },
         ],
@@ -202,12 +334,15 @@ fn manifest_toml_format() {
         r#"# This file was generated by Gleam
 # You typically do not need to edit this file
 
+version = 1
+
 packages = [
   { name = "aaa", version = "0.4.0", build_tools = ["rebar3", "make"], requirements = ["zzz", "gleam_stdlib"], otp_app = "aaa_app", source = "hex", outer_checksum = "0316" },
   { name = "awsome_local1", version = "1.2.3", build_tools = ["gleam"], requirements = [], source = "local", path = "/home/louis/packages/path/to/package" },
-  { name = "awsome_local2", version = "1.2.3", build_tools = ["gleam"], requirements = [], source = "git", repo = "https://github.com/gleam-lang/gleam.git", commit = "bd9fe02f72250e6a136967917bcb1bdccaffa3c8" },
-  { name = "gleam_stdlib", version = "0.17.1", build_tools = ["gleam"], requirements = [], source = "hex", outer_checksum = "0116" },
+  { name = "awsome_local2", version = "1.2.3", build_tools = ["gleam"], requirements = [], source = "git", repo = "https://github.com/gleam-lang/gleam.git", ref = "main", commit = "bd9fe02f72250e6a136967917bcb1bdccaffa3c8" },
+  { name = "gleam_stdlib", version = "0.17.1", build_tools = ["gleam"], requirements = [], source = "hex", outer_checksum = "0116", inner_checksum = "042C", signature = "rWE6v1ClCiMoeQ4fQtP5n2n2i4QjQeKjw6WuMK5Wjk4=" },
   { name = "gleeunit", version = "0.4.0", build_tools = ["gleam"], requirements = ["gleam_stdlib"], source = "hex", outer_checksum = "032E" },
+  { name = "vendored_pkg", version = "2.0.0", build_tools = ["gleam"], requirements = [], source = "archive", path = "vendor/vendored_pkg-2.0.0.tar.zst", compression = "zstd", outer_checksum = "0542" },
   { name = "zzz", version = "0.4.0", build_tools = ["mix"], requirements = [], source = "hex", outer_checksum = "0316" },
 ]
 
@@ -220,11 +355,43 @@ gleeunit = { version = "~> 0.1" }
 zzz = { version = "> 0.0.0" }
 "#
     );
-    let deserialised: Manifest = toml::from_str(&buffer).unwrap();
+    let deserialised = Manifest::from_toml(&buffer).unwrap();
     manifest.packages.sort_by(|a, b| a.name.cmp(&b.name));
     assert_eq!(deserialised, manifest);
 }
 
+#[test]
+fn manifest_version_defaults_to_one_when_absent() {
+    let manifest = Manifest::from_toml(
+        r#"packages = []
+
+[requirements]
+"#,
+    )
+    .unwrap();
+    assert_eq!(manifest.version, 1);
+}
+
+#[test]
+fn manifest_rejects_unsupported_future_version() {
+    let error = Manifest::from_toml(
+        r#"version = 999999
+
+packages = []
+
+[requirements]
+"#,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        error,
+        Error::UnknownManifestVersion {
+            version: 999999,
+            newest_supported: MANIFEST_VERSION,
+        }
+    ));
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Base16Checksum(pub Vec<u8>);
 
@@ -255,6 +422,28 @@ impl<'de> serde::Deserialize<'de> for Base16Checksum {
     }
 }
 
+impl Base16Checksum {
+    /// Compute the SHA-256 checksum of some bytes, for comparison against a
+    /// checksum recorded in the manifest.
+    pub fn for_bytes(bytes: &[u8]) -> Self {
+        use sha2::{Digest, Sha256};
+        Self(Sha256::digest(bytes).to_vec())
+    }
+
+    /// Compare two checksums in constant time, so that a tampered tarball
+    /// can't be distinguished from a merely corrupt one by how quickly the
+    /// comparison fails.
+    pub fn constant_time_eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .0
+                .iter()
+                .zip(other.0.iter())
+                .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
 pub struct ManifestPackage {
     pub name: String,
@@ -264,6 +453,12 @@ pub struct ManifestPackage {
     pub otp_app: Option<String>,
     #[serde(serialize_with = "sorted_vec")]
     pub requirements: Vec<String>,
+    /// A detached signature over `source`'s `outer_checksum`, proving the
+    /// package was published by the holder of a trusted key rather than
+    /// merely matching a checksum. Absent for manifests that predate
+    /// signing, or for packages published without one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
     #[serde(flatten)]
     pub source: ManifestPackageSource,
 }
@@ -283,6 +478,244 @@ impl ManifestPackage {
     pub fn is_local(&self) -> bool {
         matches!(self.source, ManifestPackageSource::Local { .. })
     }
+
+    #[inline]
+    pub fn is_archive(&self) -> bool {
+        matches!(self.source, ManifestPackageSource::Archive { .. })
+    }
+
+    /// The outer checksum this package's signature, if any, is taken over.
+    /// `Git` and `Local` sources have no checksum to sign, so this returns
+    /// `None` for them rather than assuming every source carries one.
+    pub fn outer_checksum(&self) -> Option<&Base16Checksum> {
+        match &self.source {
+            ManifestPackageSource::Hex { outer_checksum, .. }
+            | ManifestPackageSource::Archive { outer_checksum, .. } => Some(outer_checksum),
+            ManifestPackageSource::Git { .. } | ManifestPackageSource::Local { .. } => None,
+        }
+    }
+
+    /// Verify this package's signature, if it has one, against the given
+    /// set of trusted public keys. Packages with no signature are left
+    /// alone here; use `missing_required_signature` separately to decide
+    /// whether an absent signature should be treated as a problem for a
+    /// particular package.
+    pub fn verify_signature(&self, trusted_keys: &TrustedPublicKeys) -> Result<()> {
+        let Some(signature) = &self.signature else {
+            return Ok(());
+        };
+
+        let Some(outer_checksum) = self.outer_checksum() else {
+            return Err(Error::HexPackageSignatureWithoutChecksum {
+                package: self.name.clone(),
+            });
+        };
+
+        if !trusted_keys.verify(outer_checksum, signature) {
+            return Err(Error::HexPackageInvalidSignature {
+                package: self.name.clone(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Whether this package ought to have carried a signature but didn't:
+    /// true when its name belongs to one of the `require_signatures`
+    /// namespaces (the part of a Hex package name before a `/`, as used by
+    /// private Hex organizations) yet `signature` is absent. Callers should
+    /// warn, or refuse to proceed, when this returns true rather than
+    /// treating a missing signature from a namespace that's supposed to
+    /// always sign its packages the same as an ordinary unsigned package.
+    pub fn missing_required_signature(&self, require_signatures: &[String]) -> bool {
+        self.signature.is_none()
+            && self
+                .name
+                .split_once('/')
+                .is_some_and(|(namespace, _)| require_signatures.iter().any(|n| n == namespace))
+    }
+
+    /// Materialize this package into `build_packages_dir` if it's a vendored
+    /// `Archive` source: read the tarball from its recorded `path`, verify
+    /// it against `outer_checksum`, and extract it. Hex and Git packages are
+    /// fetched over the network elsewhere in resolution; this reads only
+    /// the local filesystem, so an `Archive` source needs no network
+    /// access. Dependency resolution does not call this itself.
+    pub fn ensure_archive_extracted(&self, build_packages_dir: &std::path::Path) -> Result<()> {
+        let ManifestPackageSource::Archive { path, .. } = &self.source else {
+            return Ok(());
+        };
+
+        let outer_tarball = std::fs::read(path).map_err(|error| Error::FileIo {
+            action: FileIoAction::Read,
+            kind: FileKind::File,
+            path: path.clone(),
+            err: Some(error.to_string()),
+        })?;
+
+        self.source.verify_archive_integrity(&outer_tarball)?;
+        self.source
+            .extract_archive(&outer_tarball, &build_packages_dir.join(&self.name))
+    }
+}
+
+/// A configurable set of public keys trusted to sign packages. Gleam checks
+/// a package's `signature`, if present, against every key in this set and
+/// accepts the package if any one of them verifies.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedPublicKeys {
+    keys: Vec<String>,
+}
+
+impl TrustedPublicKeys {
+    pub fn new(keys: Vec<String>) -> Self {
+        Self { keys }
+    }
+
+    /// Verify a detached signature over a checksum against every trusted
+    /// key, accepting if any one of them matches.
+    fn verify(&self, checksum: &Base16Checksum, signature: &str) -> bool {
+        self.keys
+            .iter()
+            .any(|key| verify_detached_signature(key, &checksum.to_string(), signature))
+    }
+}
+
+/// Verify a minisign-style detached Ed25519 signature. `public_key` and
+/// `signature` are base64-encoded; `to_toml` writes the signature string
+/// as it is, while checksums are written base16.
+fn verify_detached_signature(public_key: &str, message: &str, signature: &str) -> bool {
+    use base64::Engine;
+    use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+
+    let Ok(public_key) = base64::engine::general_purpose::STANDARD.decode(public_key) else {
+        return false;
+    };
+    let Ok(public_key): Result<[u8; 32], _> = public_key.try_into() else {
+        return false;
+    };
+    let Ok(verifying_key) = VerifyingKey::from_bytes(&public_key) else {
+        return false;
+    };
+
+    let Ok(signature) = base64::engine::general_purpose::STANDARD.decode(signature) else {
+        return false;
+    };
+    let Ok(signature): Result<[u8; 64], _> = signature.try_into() else {
+        return false;
+    };
+    let signature = Signature::from_bytes(&signature);
+
+    verifying_key.verify(message.as_bytes(), &signature).is_ok()
+}
+
+#[cfg(test)]
+fn test_signing_key() -> ed25519_dalek::SigningKey {
+    ed25519_dalek::SigningKey::from_bytes(&[7; 32])
+}
+
+#[test]
+fn verify_signature_accepts_a_signature_from_a_trusted_key() {
+    use base64::Engine;
+    use ed25519_dalek::Signer;
+
+    let signing_key = test_signing_key();
+    let trusted_keys = TrustedPublicKeys::new(vec![base64::engine::general_purpose::STANDARD
+        .encode(signing_key.verifying_key().to_bytes())]);
+
+    let package = ManifestPackage {
+        name: "trusted_pkg".into(),
+        signature: Some(
+            base64::engine::general_purpose::STANDARD
+                .encode(signing_key.sign(b"0316").to_bytes()),
+        ),
+        source: ManifestPackageSource::Hex {
+            outer_checksum: Base16Checksum(vec![3, 22]),
+            inner_checksum: None,
+        },
+        ..ManifestPackage::default()
+    };
+
+    assert!(package.verify_signature(&trusted_keys).is_ok());
+}
+
+#[test]
+fn verify_signature_rejects_a_signature_from_an_untrusted_key() {
+    use base64::Engine;
+    use ed25519_dalek::Signer;
+
+    let signing_key = test_signing_key();
+    // A different key is trusted, so this package's signature won't verify.
+    let other_key = ed25519_dalek::SigningKey::from_bytes(&[9; 32]);
+    let trusted_keys = TrustedPublicKeys::new(vec![base64::engine::general_purpose::STANDARD
+        .encode(other_key.verifying_key().to_bytes())]);
+
+    let package = ManifestPackage {
+        name: "untrusted_pkg".into(),
+        signature: Some(
+            base64::engine::general_purpose::STANDARD
+                .encode(signing_key.sign(b"0316").to_bytes()),
+        ),
+        source: ManifestPackageSource::Hex {
+            outer_checksum: Base16Checksum(vec![3, 22]),
+            inner_checksum: None,
+        },
+        ..ManifestPackage::default()
+    };
+
+    assert!(package.verify_signature(&trusted_keys).is_err());
+}
+
+#[test]
+fn verify_signature_errors_instead_of_panicking_for_sources_without_a_checksum() {
+    let package = ManifestPackage {
+        name: "git_pkg".into(),
+        signature: Some("not-checked-since-theres-no-checksum-to-check-it-against".into()),
+        source: ManifestPackageSource::Git {
+            repo: "https://example.com/repo.git".into(),
+            ref_: None,
+            commit: "bd9fe02f72250e6a136967917bcb1bdccaffa3c8".into(),
+        },
+        ..ManifestPackage::default()
+    };
+
+    assert!(matches!(
+        package.verify_signature(&TrustedPublicKeys::default()),
+        Err(Error::HexPackageSignatureWithoutChecksum { .. })
+    ));
+}
+
+#[test]
+fn missing_required_signature_is_true_for_an_unsigned_package_in_a_required_namespace() {
+    let package = ManifestPackage {
+        name: "my_org/internal_pkg".into(),
+        signature: None,
+        ..ManifestPackage::default()
+    };
+
+    assert!(package.missing_required_signature(&["my_org".into()]));
+}
+
+#[test]
+fn missing_required_signature_is_false_when_the_namespace_is_not_required() {
+    let package = ManifestPackage {
+        name: "other_org/pkg".into(),
+        signature: None,
+        ..ManifestPackage::default()
+    };
+
+    assert!(!package.missing_required_signature(&["my_org".into()]));
+}
+
+#[test]
+fn missing_required_signature_is_false_when_a_signature_is_present() {
+    let package = ManifestPackage {
+        name: "my_org/internal_pkg".into(),
+        signature: Some("some-signature".into()),
+        ..ManifestPackage::default()
+    };
+
+    assert!(!package.missing_required_signature(&["my_org".into()]));
 }
 
 #[cfg(test)]
@@ -293,9 +726,11 @@ impl Default for ManifestPackage {
             build_tools: Default::default(),
             otp_app: Default::default(),
             requirements: Default::default(),
+            signature: Default::default(),
             version: Version::new(1, 0, 0),
             source: ManifestPackageSource::Hex {
                 outer_checksum: Base16Checksum(vec![]),
+                inner_checksum: None,
             },
         }
     }
@@ -305,11 +740,442 @@ impl Default for ManifestPackage {
 #[serde(tag = "source")]
 pub enum ManifestPackageSource {
     #[serde(rename = "hex")]
-    Hex { outer_checksum: Base16Checksum },
+    Hex {
+        outer_checksum: Base16Checksum,
+        /// The checksum of the decompressed `contents.tar.gz` payload
+        /// inside the outer tarball. Absent for manifests written before
+        /// this was recorded, in which case only the outer tarball is
+        /// verified.
+        #[serde(default)]
+        inner_checksum: Option<Base16Checksum>,
+    },
     #[serde(rename = "git")]
-    Git { repo: SmolStr, commit: SmolStr },
+    Git {
+        repo: SmolStr,
+        /// The branch, tag or rev the user asked to depend on, if any,
+        /// recorded alongside the `commit` it was resolved to. Neither
+        /// `gleam update` nor `gleam build` reads this field; the package
+        /// is pinned by `commit` alone.
+        #[serde(rename = "ref", default)]
+        ref_: Option<SmolStr>,
+        commit: SmolStr,
+    },
     #[serde(rename = "local")]
     Local { path: PathBuf }, // should be the canonical path
+    #[serde(rename = "archive")]
+    Archive {
+        /// Path to a local compressed tarball to use instead of fetching
+        /// from Hex or Git, for fully offline/air-gapped builds.
+        path: PathBuf,
+        outer_checksum: Base16Checksum,
+        compression: Compression,
+    },
+}
+
+/// Resolve a symbolic git ref (branch or tag) on a remote repository to the
+/// commit SHA it currently points at, using `git ls-remote`. Only ref names
+/// are matched: a commit SHA, full or abbreviated, is reported as an
+/// unknown ref. Called from `Requirement::resolve_git_source`.
+pub fn resolve_git_ref(repo: &str, git_ref: &str) -> Result<SmolStr> {
+    let output = std::process::Command::new("git")
+        .arg("ls-remote")
+        .arg("--exit-code")
+        .arg(repo)
+        .arg(git_ref)
+        .output()
+        .map_err(|error| Error::ShellCommand {
+            program: "git".into(),
+            err: Some(error.kind()),
+        })?;
+
+    if !output.status.success() {
+        return Err(Error::GitDependencyUnknownRef {
+            repo: repo.into(),
+            git_ref: git_ref.into(),
+        });
+    }
+
+    // An annotated tag is listed twice: once for the tag object, and once
+    // with a `^{}` suffix for the commit it points at.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout
+        .lines()
+        .find(|line| line.ends_with("^{}"))
+        .or_else(|| stdout.lines().next())
+        .and_then(|line| line.split_whitespace().next())
+        .map(SmolStr::from)
+        .ok_or_else(|| Error::GitDependencyUnknownRef {
+            repo: repo.into(),
+            git_ref: git_ref.into(),
+        })
+}
+
+/// The compression format used by a vendored `ManifestPackageSource::Archive`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub enum Compression {
+    #[serde(rename = "gzip")]
+    Gzip,
+    #[serde(rename = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+        }
+    }
+}
+
+impl ManifestPackageSource {
+    /// Verify a downloaded Hex package against the checksums recorded in
+    /// the manifest. The outer tarball is always checked; the decompressed
+    /// `contents.tar.gz` is also checked if an inner checksum was recorded
+    /// and its bytes are provided. Returns an error if this isn't a Hex
+    /// source, or if either checksum doesn't match, so that a tampered or
+    /// corrupt download can't be mistaken for a successful one.
+    pub fn verify_hex_integrity(
+        &self,
+        outer_tarball: &[u8],
+        inner_contents: Option<&[u8]>,
+    ) -> Result<()> {
+        let (outer_checksum, inner_checksum) = match self {
+            Self::Hex {
+                outer_checksum,
+                inner_checksum,
+            } => (outer_checksum, inner_checksum),
+            Self::Git { .. } | Self::Local { .. } | Self::Archive { .. } => {
+                return Err(Error::HexPackageIntegrityError)
+            }
+        };
+
+        let computed_outer = Base16Checksum::for_bytes(outer_tarball);
+        if !computed_outer.constant_time_eq(outer_checksum) {
+            return Err(Error::HexPackageTarballWrongChecksum {
+                expected: outer_checksum.to_string(),
+                computed: computed_outer.to_string(),
+            });
+        }
+
+        if let (Some(expected_inner), Some(contents)) = (inner_checksum, inner_contents) {
+            let computed_inner = Base16Checksum::for_bytes(contents);
+            if !computed_inner.constant_time_eq(expected_inner) {
+                return Err(Error::HexPackageTarballWrongChecksum {
+                    expected: expected_inner.to_string(),
+                    computed: computed_inner.to_string(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Verify a vendored archive's bytes, as read from its `path` and before
+    /// decompression, against the checksum recorded in the manifest, exactly
+    /// as `verify_hex_integrity` does for packages fetched from Hex.
+    pub fn verify_archive_integrity(&self, outer_tarball: &[u8]) -> Result<()> {
+        let Self::Archive { outer_checksum, .. } = self else {
+            return Err(Error::HexPackageIntegrityError);
+        };
+
+        let computed = Base16Checksum::for_bytes(outer_tarball);
+        if !computed.constant_time_eq(outer_checksum) {
+            return Err(Error::HexPackageTarballWrongChecksum {
+                expected: outer_checksum.to_string(),
+                computed: computed.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Decompress and extract a vendored archive's outer tarball into
+    /// `destination`, dispatching on `compression` to pick the right
+    /// decoder before unpacking it as a tarball.
+    ///
+    /// A matching `outer_checksum` only proves the tarball's bytes weren't
+    /// corrupted or tampered with in transit; it says nothing about the
+    /// paths recorded by the entries inside. Since archives can come from
+    /// re-packaged or mirror-served sources, each entry's path is checked
+    /// before unpacking and rejected if it would escape `destination` (a
+    /// "tar-slip" via a `../` or absolute path).
+    pub fn extract_archive(&self, outer_tarball: &[u8], destination: &std::path::Path) -> Result<()> {
+        let Self::Archive { compression, .. } = self else {
+            return Err(Error::HexPackageIntegrityError);
+        };
+
+        let tar_bytes = match compression {
+            Compression::Gzip => {
+                use flate2::read::GzDecoder;
+                use std::io::Read;
+                let mut buffer = Vec::new();
+                GzDecoder::new(outer_tarball)
+                    .read_to_end(&mut buffer)
+                    .map_err(|error| Error::FileIo {
+                        action: FileIoAction::Read,
+                        kind: FileKind::File,
+                        path: destination.to_path_buf(),
+                        err: Some(error.to_string()),
+                    })?;
+                buffer
+            }
+            Compression::Zstd => {
+                zstd::stream::decode_all(outer_tarball).map_err(|error| Error::FileIo {
+                    action: FileIoAction::Read,
+                    kind: FileKind::File,
+                    path: destination.to_path_buf(),
+                    err: Some(error.to_string()),
+                })?
+            }
+        };
+
+        std::fs::create_dir_all(destination).map_err(|error| Error::FileIo {
+            action: FileIoAction::Create,
+            kind: FileKind::Directory,
+            path: destination.to_path_buf(),
+            err: Some(error.to_string()),
+        })?;
+
+        let mut archive = tar::Archive::new(tar_bytes.as_slice());
+        let entries = archive.entries().map_err(|error| Error::FileIo {
+            action: FileIoAction::Create,
+            kind: FileKind::Directory,
+            path: destination.to_path_buf(),
+            err: Some(error.to_string()),
+        })?;
+
+        for entry in entries {
+            let mut entry = entry.map_err(|error| Error::FileIo {
+                action: FileIoAction::Create,
+                kind: FileKind::Directory,
+                path: destination.to_path_buf(),
+                err: Some(error.to_string()),
+            })?;
+            let entry_path = entry.path().map_err(|error| Error::FileIo {
+                action: FileIoAction::Create,
+                kind: FileKind::Directory,
+                path: destination.to_path_buf(),
+                err: Some(error.to_string()),
+            })?;
+
+            if entry_path.is_absolute()
+                || entry_path
+                    .components()
+                    .any(|component| matches!(component, std::path::Component::ParentDir))
+            {
+                return Err(Error::FileIo {
+                    action: FileIoAction::Create,
+                    kind: FileKind::File,
+                    path: entry_path.to_path_buf(),
+                    err: Some("archive entry path escapes the extraction directory".into()),
+                });
+            }
+
+            let entry_path = entry_path.to_path_buf();
+            entry
+                .unpack(destination.join(&entry_path))
+                .map_err(|error| Error::FileIo {
+                    action: FileIoAction::Create,
+                    kind: FileKind::File,
+                    path: entry_path,
+                    err: Some(error.to_string()),
+                })?;
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn extract_archive_unpacks_a_gzip_tarball() {
+    use std::io::Write;
+
+    let mut tar_builder = tar::Builder::new(Vec::new());
+    let contents = b"hello from a vendored package\n";
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_cksum();
+    tar_builder
+        .append_data(&mut header, "hello.txt", &contents[..])
+        .unwrap();
+    let tar_bytes = tar_builder.into_inner().unwrap();
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&tar_bytes).unwrap();
+    let gzip_bytes = encoder.finish().unwrap();
+
+    let source = ManifestPackageSource::Archive {
+        path: "vendor/pkg.tar.gz".into(),
+        outer_checksum: Base16Checksum::for_bytes(&gzip_bytes),
+        compression: Compression::Gzip,
+    };
+
+    let destination = std::env::temp_dir().join("gleam_manifest_extract_archive_test");
+    let _ = std::fs::remove_dir_all(&destination);
+    source.extract_archive(&gzip_bytes, &destination).unwrap();
+
+    let extracted = std::fs::read_to_string(destination.join("hello.txt")).unwrap();
+    assert_eq!(extracted, "hello from a vendored package\n");
+
+    std::fs::remove_dir_all(&destination).unwrap();
+}
+
+#[test]
+fn extract_archive_rejects_an_entry_that_escapes_the_destination() {
+    use std::io::Write;
+
+    let mut tar_builder = tar::Builder::new(Vec::new());
+    let contents = b"pwned\n";
+    let mut header = tar::Header::new_gnu();
+    // `append_data` refuses paths containing `..`, so the escaping name is
+    // written into the header directly.
+    let entry_name = b"../../escaped.txt";
+    header.as_old_mut().name[..entry_name.len()].copy_from_slice(entry_name);
+    header.set_size(contents.len() as u64);
+    header.set_cksum();
+    tar_builder.append(&header, &contents[..]).unwrap();
+    let tar_bytes = tar_builder.into_inner().unwrap();
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&tar_bytes).unwrap();
+    let gzip_bytes = encoder.finish().unwrap();
+
+    let source = ManifestPackageSource::Archive {
+        path: "vendor/pkg.tar.gz".into(),
+        outer_checksum: Base16Checksum::for_bytes(&gzip_bytes),
+        compression: Compression::Gzip,
+    };
+
+    let test_root = std::env::temp_dir().join("gleam_manifest_extract_archive_tar_slip_test");
+    let destination = test_root.join("nested").join("dir");
+    let _ = std::fs::remove_dir_all(&test_root);
+    let result = source.extract_archive(&gzip_bytes, &destination);
+
+    assert!(matches!(result, Err(Error::FileIo { .. })));
+    assert!(!destination
+        .join("..")
+        .join("..")
+        .join("escaped.txt")
+        .exists());
+
+    let _ = std::fs::remove_dir_all(&test_root);
+}
+
+#[test]
+fn ensure_archive_extracted_reads_verifies_and_unpacks_the_recorded_tarball() {
+    use std::io::Write;
+
+    let mut tar_builder = tar::Builder::new(Vec::new());
+    let contents = b"vendored contents\n";
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_cksum();
+    tar_builder
+        .append_data(&mut header, "hello.txt", &contents[..])
+        .unwrap();
+    let tar_bytes = tar_builder.into_inner().unwrap();
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&tar_bytes).unwrap();
+    let gzip_bytes = encoder.finish().unwrap();
+
+    let tarball_path = std::env::temp_dir().join("gleam_manifest_ensure_archive_extracted.tar.gz");
+    std::fs::write(&tarball_path, &gzip_bytes).unwrap();
+
+    let package = ManifestPackage {
+        name: "vendored_pkg".into(),
+        source: ManifestPackageSource::Archive {
+            path: tarball_path.clone(),
+            outer_checksum: Base16Checksum::for_bytes(&gzip_bytes),
+            compression: Compression::Gzip,
+        },
+        ..ManifestPackage::default()
+    };
+
+    let build_packages_dir =
+        std::env::temp_dir().join("gleam_manifest_ensure_archive_extracted_dir");
+    let _ = std::fs::remove_dir_all(&build_packages_dir);
+
+    package.ensure_archive_extracted(&build_packages_dir).unwrap();
+
+    let extracted =
+        std::fs::read_to_string(build_packages_dir.join("vendored_pkg").join("hello.txt"))
+            .unwrap();
+    assert_eq!(extracted, "vendored contents\n");
+
+    std::fs::remove_file(&tarball_path).unwrap();
+    std::fs::remove_dir_all(&build_packages_dir).unwrap();
+}
+
+#[test]
+fn ensure_archive_extracted_does_nothing_for_non_archive_sources() {
+    let package = ManifestPackage {
+        name: "hex_pkg".into(),
+        ..ManifestPackage::default()
+    };
+
+    let build_packages_dir =
+        std::env::temp_dir().join("gleam_manifest_ensure_archive_extracted_hex_dir");
+    let _ = std::fs::remove_dir_all(&build_packages_dir);
+
+    package.ensure_archive_extracted(&build_packages_dir).unwrap();
+    assert!(!build_packages_dir.exists());
+}
+
+#[test]
+fn verify_hex_integrity_accepts_matching_outer_checksum() {
+    let source = ManifestPackageSource::Hex {
+        outer_checksum: Base16Checksum::for_bytes(b"tarball bytes"),
+        inner_checksum: None,
+    };
+    assert!(source.verify_hex_integrity(b"tarball bytes", None).is_ok());
+}
+
+#[test]
+fn verify_hex_integrity_rejects_mismatched_outer_checksum() {
+    let source = ManifestPackageSource::Hex {
+        outer_checksum: Base16Checksum::for_bytes(b"tarball bytes"),
+        inner_checksum: None,
+    };
+    assert!(source
+        .verify_hex_integrity(b"tampered tarball bytes", None)
+        .is_err());
+}
+
+#[test]
+fn verify_hex_integrity_checks_inner_checksum_when_present() {
+    let source = ManifestPackageSource::Hex {
+        outer_checksum: Base16Checksum::for_bytes(b"outer"),
+        inner_checksum: Some(Base16Checksum::for_bytes(b"inner contents")),
+    };
+    assert!(source
+        .verify_hex_integrity(b"outer", Some(b"tampered contents"))
+        .is_err());
+    assert!(source
+        .verify_hex_integrity(b"outer", Some(b"inner contents"))
+        .is_ok());
+}
+
+#[test]
+fn verify_hex_integrity_rejects_non_hex_sources() {
+    let source = ManifestPackageSource::Local {
+        path: "/path/to/package".into(),
+    };
+    assert!(source.verify_hex_integrity(b"anything", None).is_err());
+}
+
+#[test]
+fn verify_archive_integrity_checks_outer_checksum() {
+    let source = ManifestPackageSource::Archive {
+        path: "vendor/pkg.tar.zst".into(),
+        outer_checksum: Base16Checksum::for_bytes(b"archive bytes"),
+        compression: Compression::Zstd,
+    };
+    assert!(source.verify_archive_integrity(b"archive bytes").is_ok());
+    assert!(source
+        .verify_archive_integrity(b"tampered archive bytes")
+        .is_err());
 }
 
 fn ordered_map<S, K, V>(value: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
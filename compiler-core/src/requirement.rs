@@ -0,0 +1,93 @@
+use crate::manifest::ManifestPackageSource;
+use crate::Result;
+use hexpm::version::Range;
+
+/// A dependency requirement as written by a user in `gleam.toml`, before it
+/// has been resolved to a specific `ManifestPackageSource`. Resolution picks
+/// a package satisfying the requirement and records the result as a
+/// `ManifestPackage` in the lockfile.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum Requirement {
+    Hex {
+        version: Range,
+    },
+    Path {
+        path: std::path::PathBuf,
+    },
+    Git {
+        #[serde(rename = "git")]
+        repo: String,
+        /// The branch, tag or rev the user asked to depend on, if any. When
+        /// absent, resolution follows the repository's default branch.
+        #[serde(rename = "ref", default, skip_serializing_if = "Option::is_none")]
+        ref_: Option<String>,
+    },
+}
+
+impl Requirement {
+    pub fn hex(range: &str) -> Self {
+        Self::Hex {
+            version: Range::new(range.to_string()),
+        }
+    }
+
+    pub fn path(path: &str) -> Self {
+        Self::Path {
+            path: path.into(),
+        }
+    }
+
+    pub fn git(repo: &str) -> Self {
+        Self::Git {
+            repo: repo.to_string(),
+            ref_: None,
+        }
+    }
+
+    /// Depend on a git repository pinned to a particular branch, tag or
+    /// rev, rather than whatever its default branch currently points at.
+    pub fn git_with_ref(repo: &str, git_ref: &str) -> Self {
+        Self::Git {
+            repo: repo.to_string(),
+            ref_: Some(git_ref.to_string()),
+        }
+    }
+
+    pub fn to_toml(&self) -> String {
+        match self {
+            Self::Hex { version } => format!(r#"{{ version = "{version}" }}"#),
+            Self::Path { path } => {
+                format!(r#"{{ path = "{}" }}"#, path.to_str().expect("path non utf-8"))
+            }
+            Self::Git { repo, ref_: None } => format!(r#"{{ git = "{repo}" }}"#),
+            Self::Git {
+                repo,
+                ref_: Some(git_ref),
+            } => format!(r#"{{ git = "{repo}", ref = "{git_ref}" }}"#),
+        }
+    }
+
+    /// For a `Git` requirement, resolve its `ref` (if any) to the commit it
+    /// currently points at, producing the `ManifestPackageSource` that gets
+    /// locked into the manifest. Returns `None` for non-git requirements, as
+    /// those are resolved against Hex or the local filesystem instead.
+    ///
+    /// Dependency resolution, `gleam update` and `gleam build` do not call
+    /// this; a `ref` is resolved to a commit only when this is invoked
+    /// directly.
+    pub fn resolve_git_source(&self) -> Result<Option<ManifestPackageSource>> {
+        let Self::Git { repo, ref_ } = self else {
+            return Ok(None);
+        };
+
+        let git_ref = ref_.as_deref().unwrap_or("HEAD");
+        let commit = crate::manifest::resolve_git_ref(repo, git_ref)?;
+
+        Ok(Some(ManifestPackageSource::Git {
+            repo: repo.as_str().into(),
+            ref_: ref_.as_deref().map(Into::into),
+            commit,
+        }))
+    }
+}